@@ -18,7 +18,7 @@ fn main() {
     let graphics = Rc::new(gpu::Graphics::new(&system).expect("Cannot open graphics."));
 
     // create game engine
-    let engine = Rc::new(Engine::new(&system,&graphics,vec2!(1024,576),vec2!(256,144)).expect("Cannot open engine."));
+    let engine = Rc::new(Engine::new(&system,&graphics,vec2!(1024,576),vec2!(256,144),false).expect("Cannot open engine."));
     
     // load image
     let image = imageformats::load("try/256x144.png").expect("cannot read image");
@@ -0,0 +1,227 @@
+// G - texture array atlas
+// Desmond Germans, 2020
+
+use crate::*;
+use std::rc::Rc;
+
+/// Sub-image rectangle inside a `TextureArrayAtlas`.
+///
+/// `layer` is the array slice the sub-image lives in, the `u`/`v` pair are the
+/// normalized texture coordinates of the top-left and bottom-right corners.
+#[derive(Copy,Clone,Debug)]
+pub struct AtlasRegion {
+    pub layer: usize,
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32,
+}
+
+/// One row inside a layer, ordered by height.
+struct Shelf {
+    y: i32,
+    h: i32,
+    x: i32,
+}
+
+/// One `GL_TEXTURE_2D_ARRAY` slice and its shelves.
+struct AtlasLayer {
+    shelves: Vec<Shelf>,
+    y_cursor: i32,
+}
+
+impl AtlasLayer {
+    fn new() -> AtlasLayer {
+        AtlasLayer {
+            shelves: Vec::new(),
+            y_cursor: 0,
+        }
+    }
+}
+
+/// Dynamic texture array atlas for tiles and sprites.
+///
+/// Sub-images of arbitrary size are shelf-packed into a `GL_TEXTURE_2D_ARRAY`:
+/// each layer keeps a list of shelves (rows) ordered by height, a region is
+/// placed on the first shelf that still has room for it with minimal height
+/// waste, otherwise a new shelf is opened at the layer's y-cursor. When a layer
+/// is full a new array slice is allocated.
+pub struct TextureArrayAtlas {
+    _graphics: Rc<gpu::Graphics>,
+    tex: gl::types::GLuint,
+    layers: Vec<AtlasLayer>,
+    regions: Vec<AtlasRegion>,
+}
+
+/// Edge of the square atlas layers, in texels.
+const ATLAS_SIZE: i32 = 2048;
+
+impl TextureArrayAtlas {
+    /// Create new, empty texture array atlas.
+    /// # Arguments
+    /// * `graphics` - GPU Graphics context to create the atlas for.
+    pub fn new(graphics: &Rc<gpu::Graphics>) -> Result<TextureArrayAtlas,EngineError> {
+        let mut tex: gl::types::GLuint = 0;
+        unsafe {
+            gl::GenTextures(1,&mut tex);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY,tex);
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                gl::RGBA8 as gl::types::GLint,
+                ATLAS_SIZE,ATLAS_SIZE,1,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_MIN_FILTER,gl::NEAREST as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_MAG_FILTER,gl::NEAREST as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_WRAP_S,gl::CLAMP_TO_EDGE as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_WRAP_T,gl::CLAMP_TO_EDGE as gl::types::GLint);
+        }
+        Ok(TextureArrayAtlas {
+            _graphics: Rc::clone(graphics),
+            tex: tex,
+            layers: vec![AtlasLayer::new()],
+            regions: Vec::new(),
+        })
+    }
+
+    /// Grow the array by one slice, preserving the existing layers.
+    fn grow(&mut self) {
+        let old = self.tex;
+        let old_depth = self.layers.len() as gl::types::GLsizei;
+        let new_depth = old_depth + 1;
+        let mut tex: gl::types::GLuint = 0;
+        unsafe {
+            gl::GenTextures(1,&mut tex);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY,tex);
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                gl::RGBA8 as gl::types::GLint,
+                ATLAS_SIZE,ATLAS_SIZE,new_depth,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_MIN_FILTER,gl::NEAREST as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_MAG_FILTER,gl::NEAREST as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_WRAP_S,gl::CLAMP_TO_EDGE as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY,gl::TEXTURE_WRAP_T,gl::CLAMP_TO_EDGE as gl::types::GLint);
+            gl::CopyImageSubData(
+                old,gl::TEXTURE_2D_ARRAY,0,0,0,0,
+                tex,gl::TEXTURE_2D_ARRAY,0,0,0,0,
+                ATLAS_SIZE,ATLAS_SIZE,old_depth,
+            );
+            gl::DeleteTextures(1,&old);
+        }
+        self.tex = tex;
+        self.layers.push(AtlasLayer::new());
+    }
+
+    /// Allocate a sub-image in the atlas and upload its pixels.
+    /// # Arguments
+    /// * `image` - Sub-image to pack into the atlas.
+    /// # Returns
+    /// The `AtlasRegion` describing where the sub-image landed.
+    pub fn allocate(&mut self,image: &Mat<pixel::ARGB8>) -> AtlasRegion {
+        let w = image.size.x() as i32;
+        let h = image.size.y() as i32;
+        assert!(w <= ATLAS_SIZE && h <= ATLAS_SIZE,"TextureArrayAtlas::allocate: sub-image {}x{} exceeds atlas size {}",w,h,ATLAS_SIZE);
+
+        // find the first existing shelf that fits with minimal height waste
+        let mut placed: Option<(usize,usize)> = None;
+        let mut best_waste = i32::MAX;
+        for (li,layer) in self.layers.iter().enumerate() {
+            for (si,shelf) in layer.shelves.iter().enumerate() {
+                if shelf.h >= h && ATLAS_SIZE - shelf.x >= w {
+                    let waste = shelf.h - h;
+                    if waste < best_waste {
+                        best_waste = waste;
+                        placed = Some((li,si));
+                    }
+                }
+            }
+        }
+
+        // otherwise open a new shelf, growing the array when no layer has room
+        let (li,si) = match placed {
+            Some(pos) => pos,
+            None => {
+                let mut target: Option<usize> = None;
+                for (li,layer) in self.layers.iter().enumerate() {
+                    if layer.y_cursor + h <= ATLAS_SIZE {
+                        target = Some(li);
+                        break;
+                    }
+                }
+                let li = match target {
+                    Some(li) => li,
+                    None => { self.grow(); self.layers.len() - 1 },
+                };
+                let layer = &mut self.layers[li];
+                // keep the shelves ordered by height
+                let si = layer.shelves.iter().position(|shelf| shelf.h > h).unwrap_or(layer.shelves.len());
+                layer.shelves.insert(si,Shelf { y: layer.y_cursor,h: h,x: 0 });
+                layer.y_cursor += h;
+                (li,si)
+            },
+        };
+
+        let (x,y) = {
+            let shelf = &mut self.layers[li].shelves[si];
+            let x = shelf.x;
+            shelf.x += w;
+            (x,shelf.y)
+        };
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY,self.tex);
+            gl::TexSubImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                x,y,li as gl::types::GLint,
+                w,h,1,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                image.data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+
+        let region = AtlasRegion {
+            layer: li,
+            u0: (x as f32) / (ATLAS_SIZE as f32),
+            v0: (y as f32) / (ATLAS_SIZE as f32),
+            u1: ((x + w) as f32) / (ATLAS_SIZE as f32),
+            v1: ((y + h) as f32) / (ATLAS_SIZE as f32),
+        };
+        self.regions.push(region);
+        region
+    }
+
+    /// Regions allocated so far, in allocation order.
+    pub fn regions(&self) -> &[AtlasRegion] {
+        &self.regions
+    }
+
+    /// Bind the underlying `GL_TEXTURE_2D_ARRAY` to a texture unit.
+    /// # Arguments
+    /// * `unit` - Texture unit to bind to.
+    pub fn bind(&self,unit: usize) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit as gl::types::GLenum);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY,self.tex);
+        }
+    }
+}
+
+impl Drop for TextureArrayAtlas {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1,&self.tex);
+        }
+    }
+}
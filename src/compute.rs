@@ -0,0 +1,165 @@
+// G - compute shader
+// Desmond Germans, 2020
+
+use crate::*;
+use std::rc::Rc;
+use std::marker::PhantomData;
+
+/// Shader storage buffer object wrapping a `GL_SHADER_STORAGE_BUFFER`.
+///
+/// Holds a flat array of `T` on the GPU and can be bound to an indexed binding
+/// point so compute shaders reach it through a `layout(std430, binding = N)`
+/// block.
+pub struct StorageBuffer<T> {
+    _graphics: Rc<gpu::Graphics>,
+    ssbo: gl::types::GLuint,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T> StorageBuffer<T> {
+    /// Create new storage buffer and upload the initial contents.
+    /// # Arguments
+    /// * `graphics` - GPU Graphics context to create the buffer for.
+    /// * `data` - Initial contents.
+    pub fn new(graphics: &Rc<gpu::Graphics>,data: &[T]) -> Result<StorageBuffer<T>,EngineError> {
+        let mut ssbo: gl::types::GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1,&mut ssbo);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER,ssbo);
+            gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                (data.len() * std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+        }
+        Ok(StorageBuffer {
+            _graphics: Rc::clone(graphics),
+            ssbo: ssbo,
+            len: data.len(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Overwrite part of the buffer.
+    /// # Arguments
+    /// * `start` - First element to overwrite.
+    /// * `data` - Replacement contents.
+    pub fn write(&self,start: usize,data: &[T]) {
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER,self.ssbo);
+            gl::BufferSubData(
+                gl::SHADER_STORAGE_BUFFER,
+                (start * std::mem::size_of::<T>()) as gl::types::GLintptr,
+                (data.len() * std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+    }
+
+    /// Bind the buffer to an indexed shader storage binding point.
+    /// # Arguments
+    /// * `binding` - `binding =` index the compute shader declares.
+    pub fn bind(&self,binding: usize) {
+        unsafe {
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER,binding as gl::types::GLuint,self.ssbo);
+        }
+    }
+
+    /// Number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the buffer holds no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> Drop for StorageBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1,&self.ssbo);
+        }
+    }
+}
+
+/// Compute shader program, compiled from a single `GL_COMPUTE_SHADER` source.
+pub struct ComputeShader {
+    _graphics: Rc<gpu::Graphics>,
+    sp: gl::types::GLuint,
+}
+
+impl ComputeShader {
+    /// Compile and link a compute shader.
+    /// # Arguments
+    /// * `graphics` - GPU Graphics context to create the shader for.
+    /// * `src` - GLSL compute shader source.
+    pub fn new(graphics: &Rc<gpu::Graphics>,src: &str) -> Result<ComputeShader,EngineError> {
+        unsafe {
+            let cs = gl::CreateShader(gl::COMPUTE_SHADER);
+            let length = src.len() as gl::types::GLint;
+            gl::ShaderSource(cs,1,&(src.as_ptr() as *const gl::types::GLchar),&length);
+            gl::CompileShader(cs);
+            let mut status: gl::types::GLint = 0;
+            gl::GetShaderiv(cs,gl::COMPILE_STATUS,&mut status);
+            if status == 0 {
+                gl::DeleteShader(cs);
+                return Err(EngineError::Generic);
+            }
+            let sp = gl::CreateProgram();
+            gl::AttachShader(sp,cs);
+            gl::LinkProgram(sp);
+            gl::DeleteShader(cs);
+            gl::GetProgramiv(sp,gl::LINK_STATUS,&mut status);
+            if status == 0 {
+                gl::DeleteProgram(sp);
+                return Err(EngineError::Generic);
+            }
+            Ok(ComputeShader {
+                _graphics: Rc::clone(graphics),
+                sp: sp,
+            })
+        }
+    }
+
+    /// Bind a texture as an image for read/write access from the compute shader.
+    /// # Arguments
+    /// * `unit` - Image unit the shader's `image2D` binds to.
+    /// * `texture` - Texture to expose.
+    /// * `access` - `GL_READ_ONLY`, `GL_WRITE_ONLY` or `GL_READ_WRITE`.
+    /// * `format` - Image format the shader declares (e.g. `GL_RGBA8`).
+    pub fn bind_image_texture(&self,unit: usize,texture: &e::Texture2D,access: gl::types::GLenum,format: gl::types::GLenum) {
+        unsafe {
+            gl::BindImageTexture(unit as gl::types::GLuint,texture.id(),0,gl::FALSE,0,access,format);
+        }
+    }
+
+    /// Dispatch the compute shader over a work-group grid and place a memory
+    /// barrier so later fragment stages see the results.
+    ///
+    /// Deliberately lives on `ComputeShader` rather than `Graphics`: unlike a
+    /// draw, a dispatch has no vertex/fragment state to bind through `Graphics`,
+    /// so the shader binds its own program and issues the dispatch itself.
+    /// # Arguments
+    /// * `x` - Work groups in X.
+    /// * `y` - Work groups in Y.
+    /// * `z` - Work groups in Z.
+    pub fn dispatch_compute(&self,x: usize,y: usize,z: usize) {
+        unsafe {
+            gl::UseProgram(self.sp);
+            gl::DispatchCompute(x as gl::types::GLuint,y as gl::types::GLuint,z as gl::types::GLuint);
+            gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT | gl::TEXTURE_FETCH_BARRIER_BIT);
+        }
+    }
+}
+
+impl Drop for ComputeShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.sp);
+        }
+    }
+}
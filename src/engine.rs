@@ -14,11 +14,17 @@ pub struct Engine {
     pub(crate) core: e::BaseWindow,
     pub framebuffer: Rc<gpu::Framebuffer>,
     pub(crate) layer_shader: gpu::Shader,
+    pub(crate) layer_u_texture: gpu::UniformHandle,
     pub(crate) final_shader: gpu::Shader,
+    pub(crate) final_u_texture: gpu::UniformHandle,
     pub(crate) static_shader: gpu::Shader,
+    pub(crate) static_u_texture: gpu::UniformHandle,
     pub(crate) map_shader: gpu::Shader,
     pub quad_vertexbuffer: gpu::VertexBuffer<Vec2<f32>>,
+    pub(crate) present_stream: gpu::StreamBuffer<f32>,
     pub running: Cell<bool>,
+    pub(crate) frame: Cell<usize>,
+    pub(crate) srgb: bool,
 }
 
 pub enum EngineError {
@@ -38,8 +44,14 @@ impl Engine {
     /// * `graphics` - GPU Graphics context to create game engine for.
     /// * `winsize` - Initial screen window size.
     /// * `fbsize` - Compositing framebuffer size.
-    pub fn new(system: &Rc<System>,graphics: &Rc<gpu::Graphics>,winsize: Vec2<usize>,fbsize: Vec2<usize>) -> Result<Engine,EngineError> {
+    /// * `srgb` - Composite and present in linear space with sRGB encoding; set
+    ///   to `false` for pixel-art games that want raw color values.
+    pub fn new(system: &Rc<System>,graphics: &Rc<gpu::Graphics>,winsize: Vec2<usize>,fbsize: Vec2<usize>,srgb: bool) -> Result<Engine,EngineError> {
 
+        // NOTE: in the sRGB path this stays a plain RGBA8 attachment holding
+        // linear values rather than a GL_SRGB8_ALPHA8 one, so dark tones band
+        // slightly; the explicit shader conversion is the portable fallback for
+        // backends that can't hand us an sRGB default framebuffer.
         let framebuffer = Rc::new(match gpu::Framebuffer::new(&graphics,fbsize) {
             Ok(framebuffer) => framebuffer,
             Err(_) => { return Err(EngineError::Generic); },
@@ -54,7 +66,21 @@ impl Engine {
                 gl_Position = vec4(-1.0 + 2.0 * v_pos.x,-1.0 + 2.0 * v_pos.y,0.0,1.0);
             }
         "#;
-        let layer_fs = r#"
+        // linearize the (sRGB-encoded) layer on sample so the blend into the
+        // compositing framebuffer happens in linear space
+        let layer_fs = if srgb { r#"
+            #version 420 core
+            uniform sampler2D u_texture;
+            in vec2 f_tex;
+            out vec4 fs_output;
+            vec3 srgb_to_linear(vec3 c) {
+                return mix(pow((c + 0.055) / 1.055,vec3(2.4)),c / 12.92,lessThanEqual(c,vec3(0.04045)));
+            }
+            void main() {
+                vec4 d = texture2D(u_texture,f_tex);
+                fs_output = vec4(srgb_to_linear(d.rgb),d.a);
+            }
+        "# } else { r#"
             #version 420 core
             uniform sampler2D u_texture;
             in vec2 f_tex;
@@ -62,23 +88,40 @@ impl Engine {
             void main() {
                 fs_output = texture2D(u_texture,f_tex);
             }
-        "#;
+        "# };
         let layer_shader = match gpu::Shader::new(&graphics,layer_vs,None,layer_fs) {
             Ok(shader) => shader,
             Err(_) => { return Err(EngineError::Generic); },
         };
+        let layer_u_texture = layer_shader.uniform_location("u_texture").expect("Engine::new: layer_shader has no u_texture uniform.");
 
+        // positions arrive pre-scaled from the per-frame present stream (the
+        // aspect fit and Y-flip are baked in on the CPU)
         let final_vs = r#"
             #version 420 core
-            uniform vec2 u_scale;
             layout(location = 0) in vec2 v_pos;
+            layout(location = 1) in vec2 v_tex;
             out vec2 f_tex;
             void main() {
-                f_tex = vec2(v_pos.x,v_pos.y);
-                gl_Position = vec4(u_scale.x * (-1.0 + 2.0 * v_pos.x),u_scale.y * (1.0 - 2.0 * v_pos.y),0.0,1.0);  // last stage swaps Y-output
+                f_tex = v_tex;
+                gl_Position = vec4(v_pos,0.0,1.0);
             }
         "#;
-        let final_fs = r#"
+        // the compositing framebuffer holds linear values, so the final stage
+        // encodes linear -> sRGB by hand for the (non-sRGB) default framebuffer
+        let final_fs = if srgb { r#"
+            #version 420 core
+            uniform sampler2D u_texture;
+            in vec2 f_tex;
+            layout(location = 0) out vec4 fs_output;
+            vec3 linear_to_srgb(vec3 c) {
+                return mix(1.055 * pow(c,vec3(1.0 / 2.4)) - 0.055,c * 12.92,lessThanEqual(c,vec3(0.0031308)));
+            }
+            void main() {
+                vec4 d = texture2D(u_texture,f_tex);
+                fs_output = vec4(linear_to_srgb(d.rgb),d.a);
+            }
+        "# } else { r#"
             #version 420 core
             uniform sampler2D u_texture;
             in vec2 f_tex;
@@ -86,12 +129,17 @@ impl Engine {
             void main() {
                 fs_output = texture2D(u_texture,f_tex);
             }
-        "#;
+        "# };
         let final_shader = match gpu::Shader::new(&graphics,final_vs,None,final_fs) {
             Ok(shader) => shader,
             Err(_) => { return Err(EngineError::Generic); },
         };
+        let final_u_texture = final_shader.uniform_location("u_texture").expect("Engine::new: final_shader has no u_texture uniform.");
 
+        // the static pass deliberately passes colors through untouched: the art
+        // is kept in its sRGB encoding inside the layer framebuffer and is
+        // linearized later by layer_fs when it composites, so converting here
+        // too would double-linearize.
         let static_vs = r#"
             #version 420 core
             layout(location = 0) in vec2 v_pos;
@@ -114,6 +162,7 @@ impl Engine {
             Ok(shader) => shader,
             Err(_) => { return Err(EngineError::Generic); },
         };
+        let static_u_texture = static_shader.uniform_location("u_texture").expect("Engine::new: static_shader has no u_texture uniform.");
 
         let map_vs = r#"
             #version 420 core
@@ -127,29 +176,25 @@ impl Engine {
         let map_fs = r#"
             #version 420 core
             uniform usampler2D map_texture;
-            uniform sampler2D atlas_texture;
+            uniform sampler2DArray atlas_texture;
+            uniform sampler2D region_texture;
+            uniform usampler2D layer_texture;
             uniform vec2 offset;
             uniform vec2 tiles_per_pixel;
             uniform vec2 pixels_per_layer;
             uniform vec2 maps_per_tile;
-            const uint TILES_PER_ATLAS = 32;
             in vec2 f_tex;
             out vec4 fs_output;
             void main() {
                 vec2 tc = f_tex * pixels_per_layer * tiles_per_pixel + offset;
                 vec2 mc = floor(tc) * maps_per_tile;
                 uint tile_index = texture(map_texture,mc).x;
-                vec2 tsc = vec2(
-                    float(tile_index % TILES_PER_ATLAS),
-                    float(tile_index / TILES_PER_ATLAS)
-                );
-                vec2 ftsc = tsc + fract(tc);
-                vec2 ntsc = vec2(
-                    ftsc.x / TILES_PER_ATLAS,
-                    ftsc.y / TILES_PER_ATLAS
-                );
-                vec4 d = texture(atlas_texture,ntsc);
-                fs_output = d;
+                // look up where the allocator packed this tile and sample the
+                // returned array layer at the sub-UV offset inside its region
+                vec4 region = texelFetch(region_texture,ivec2(int(tile_index),0),0);
+                uint layer = texelFetch(layer_texture,ivec2(int(tile_index),0),0).x;
+                vec2 uv = mix(region.xy,region.zw,fract(tc));
+                fs_output = texture(atlas_texture,vec3(uv,float(layer)));
             }
         "#;
         let map_shader = match gpu::Shader::new(&graphics,map_vs,None,map_fs) {
@@ -169,6 +214,14 @@ impl Engine {
             Err(_) => { return Err(EngineError::Generic); },
         };
 
+        // the present quad is rebuilt on the CPU each frame from the live
+        // window aspect, so it streams through a rotating per-frame buffer
+        // (4 vertices of interleaved position.xy + texcoord.xy)
+        let present_stream = match gpu::StreamBuffer::new(&graphics,16) {
+            Ok(streambuffer) => streambuffer,
+            Err(_) => { return Err(EngineError::Generic); },
+        };
+
         Ok(Engine {
             system: Rc::clone(system),
             graphics: Rc::clone(graphics),
@@ -179,11 +232,17 @@ impl Engine {
             ),
             framebuffer: framebuffer,
             layer_shader: layer_shader,
+            layer_u_texture: layer_u_texture,
             final_shader: final_shader,
+            final_u_texture: final_u_texture,
             static_shader: static_shader,
+            static_u_texture: static_u_texture,
             map_shader: map_shader,
             quad_vertexbuffer: quad_vertexbuffer,
+            present_stream: present_stream,
             running: Cell::new(true),
+            frame: Cell::new(0),
+            srgb: srgb,
         })
     }
 
@@ -210,21 +269,51 @@ impl Engine {
         for layer in layers.iter() {
             self.graphics.bind_texture(0,layer.framebuffer());
             self.graphics.bind_shader(&self.layer_shader);
-            self.graphics.set_uniform("u_texture",0);
+            self.graphics.set_uniform_handle(self.layer_u_texture,0);
             self.graphics.bind_vertexbuffer(&self.quad_vertexbuffer);
             self.graphics.draw_triangle_fan(4);
         }
         self.graphics.bind_target(self);
         self.graphics.bind_texture(0,&*self.framebuffer);
         self.graphics.bind_shader(&self.final_shader);
-        self.graphics.set_uniform("u_scale",scale);
-        self.graphics.set_uniform("u_texture",0);
-        self.graphics.bind_vertexbuffer(&self.quad_vertexbuffer);
-        self.graphics.draw_triangle_fan(4);
+        self.graphics.set_uniform_handle(self.final_u_texture,0);
+        self.draw_present_quad(scale);
+    }
+
+    /// Draw the final present quad through the per-frame buffer ring. The quad
+    /// is rebuilt on the CPU from the current window aspect and written once to
+    /// the slot the GPU is not reading, so the per-frame upload never stalls
+    /// against an in-flight frame.
+    fn draw_present_quad(&self,scale: Vec2<f32>) {
+        // interleaved position.xy (aspect-scaled, Y-flipped) + texcoord.xy
+        let verts: [f32; 16] = [
+            scale.x() * -1.0,scale.y() *  1.0,0.0,0.0,
+            scale.x() *  1.0,scale.y() *  1.0,1.0,0.0,
+            scale.x() *  1.0,scale.y() * -1.0,1.0,1.0,
+            scale.x() * -1.0,scale.y() * -1.0,0.0,1.0,
+        ];
+        let frame = self.frame.get();
+        self.present_stream.write(frame,&verts);
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER,self.present_stream.slot(frame));
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0,2,gl::FLOAT,gl::FALSE,16,std::ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1,2,gl::FLOAT,gl::FALSE,16,8 as *const gl::types::GLvoid);
+            gl::DrawArrays(gl::TRIANGLE_FAN,0,4);
+            gl::DisableVertexAttribArray(0);
+            gl::DisableVertexAttribArray(1);
+        }
+    }
+
+    /// Frame slot streamed buffers should target this frame.
+    pub fn frame(&self) -> usize {
+        self.frame.get()
     }
 
     pub fn present(&self) {
         self.graphics.present(self.core.id);
+        self.frame.set(self.frame.get().wrapping_add(1));
     }
 }
 
@@ -0,0 +1,225 @@
+// G - map layer
+// Desmond Germans, 2020
+
+use crate::*;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+pub struct MapLayer {
+    pub(crate) engine: Rc<Engine>,
+    pub(crate) framebuffer: Rc<e::Framebuffer>,
+    pub(crate) atlas: Rc<RefCell<TextureArrayAtlas>>,
+    pub(crate) map_texture: e::Texture2D,
+    pub(crate) region_texture: gl::types::GLuint,
+    pub(crate) layer_texture: gl::types::GLuint,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) tile_size: Cell<Vec2<f32>>,
+    pub(crate) offset: Cell<Vec2<f32>>,
+    pub(crate) u_map_texture: gpu::UniformHandle,
+    pub(crate) u_atlas_texture: gpu::UniformHandle,
+    pub(crate) u_region_texture: gpu::UniformHandle,
+    pub(crate) u_layer_texture: gpu::UniformHandle,
+    pub(crate) u_offset: gpu::UniformHandle,
+    pub(crate) u_pixels_per_layer: gpu::UniformHandle,
+    pub(crate) u_tiles_per_pixel: gpu::UniformHandle,
+    pub(crate) u_maps_per_tile: gpu::UniformHandle,
+}
+
+impl MapLayer {
+    /// Create new scrolling map layer from a grid of tile indices.
+    /// # Arguments
+    /// * `engine` - Game engine to create the layer for.
+    /// * `width` - Width of the tile map, in tiles.
+    /// * `height` - Height of the tile map, in tiles.
+    /// * `tiles` - Row-major tile indices into the layer's atlas.
+    pub fn new_from_indices(engine: &Rc<Engine>,width: usize,height: usize,tiles: Vec<u16>) -> Result<MapLayer,EngineError> {
+        let framebuffer = Rc::new(e::Framebuffer::new(&engine.graphics,engine.framebuffer.size).expect("MapLayer::new_from_indices: Unable to create framebuffer."));
+        let atlas = Rc::new(RefCell::new(TextureArrayAtlas::new(&engine.graphics).expect("MapLayer::new_from_indices: Unable to create atlas.")));
+        let map_texture = e::Texture2D::new_from_indices(&engine.graphics,width,height,tiles).expect("MapLayer::new_from_indices: Unable to create tile-index texture.");
+        let mut region_texture: gl::types::GLuint = 0;
+        let mut layer_texture: gl::types::GLuint = 0;
+        unsafe {
+            gl::GenTextures(1,&mut region_texture);
+            gl::GenTextures(1,&mut layer_texture);
+        }
+        let shader = &engine.map_shader;
+        let u_map_texture = shader.uniform_location("map_texture").expect("MapLayer::new_from_indices: map_shader has no map_texture uniform.");
+        let u_atlas_texture = shader.uniform_location("atlas_texture").expect("MapLayer::new_from_indices: map_shader has no atlas_texture uniform.");
+        let u_region_texture = shader.uniform_location("region_texture").expect("MapLayer::new_from_indices: map_shader has no region_texture uniform.");
+        let u_layer_texture = shader.uniform_location("layer_texture").expect("MapLayer::new_from_indices: map_shader has no layer_texture uniform.");
+        let u_offset = shader.uniform_location("offset").expect("MapLayer::new_from_indices: map_shader has no offset uniform.");
+        let u_pixels_per_layer = shader.uniform_location("pixels_per_layer").expect("MapLayer::new_from_indices: map_shader has no pixels_per_layer uniform.");
+        let u_tiles_per_pixel = shader.uniform_location("tiles_per_pixel").expect("MapLayer::new_from_indices: map_shader has no tiles_per_pixel uniform.");
+        let u_maps_per_tile = shader.uniform_location("maps_per_tile").expect("MapLayer::new_from_indices: map_shader has no maps_per_tile uniform.");
+        let layer = MapLayer {
+            engine: Rc::clone(engine),
+            framebuffer: framebuffer,
+            atlas: atlas,
+            map_texture: map_texture,
+            region_texture: region_texture,
+            layer_texture: layer_texture,
+            width: width,
+            height: height,
+            tile_size: Cell::new(vec2!(f32: 16.0,16.0)),
+            offset: Cell::new(vec2!(f32: 0.0,0.0)),
+            u_map_texture: u_map_texture,
+            u_atlas_texture: u_atlas_texture,
+            u_region_texture: u_region_texture,
+            u_layer_texture: u_layer_texture,
+            u_offset: u_offset,
+            u_pixels_per_layer: u_pixels_per_layer,
+            u_tiles_per_pixel: u_tiles_per_pixel,
+            u_maps_per_tile: u_maps_per_tile,
+        };
+        layer.refresh_regions();
+        Ok(layer)
+    }
+
+    /// Atlas backing this map layer's tiles.
+    pub fn atlas(&self) -> &Rc<RefCell<TextureArrayAtlas>> {
+        &self.atlas
+    }
+
+    /// Pack a tile image into the atlas and return its tile index.
+    ///
+    /// Tiles are allocated in tile-index order (the returned index is the one
+    /// `new_from_indices`/`set_tile` refer to). The region lookup the
+    /// `map_shader` samples is *not* rebuilt here; call `refresh_regions` once
+    /// after a batch of `allocate_tile` calls, or use `allocate_tiles`.
+    /// # Arguments
+    /// * `image` - Tile image to pack.
+    pub fn allocate_tile(&self,image: &Mat<pixel::ARGB8>) -> u16 {
+        let mut atlas = self.atlas.borrow_mut();
+        atlas.allocate(image);
+        (atlas.regions().len() - 1) as u16
+    }
+
+    /// Pack a batch of tile images into the atlas, rebuilding the region lookup
+    /// once, and return their tile indices in order.
+    /// # Arguments
+    /// * `images` - Tile images to pack.
+    pub fn allocate_tiles(&self,images: &[Mat<pixel::ARGB8>]) -> Vec<u16> {
+        let indices = {
+            let mut atlas = self.atlas.borrow_mut();
+            images.iter().map(|image| {
+                atlas.allocate(image);
+                (atlas.regions().len() - 1) as u16
+            }).collect()
+        };
+        self.refresh_regions();
+        indices
+    }
+
+    /// Rebuild the tile-index -> `AtlasRegion` lookup the `map_shader` samples
+    /// from the atlas's current allocations.
+    pub fn refresh_regions(&self) {
+        let atlas = self.atlas.borrow();
+        let regions = atlas.regions();
+        let count = regions.len().max(1);
+        let mut rects: Vec<f32> = vec![0.0; count * 4];
+        let mut layers: Vec<u16> = vec![0; count];
+        for (i,region) in regions.iter().enumerate() {
+            rects[i * 4] = region.u0;
+            rects[i * 4 + 1] = region.v0;
+            rects[i * 4 + 2] = region.u1;
+            rects[i * 4 + 3] = region.v1;
+            layers[i] = region.layer as u16;
+        }
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D,self.region_texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA32F as gl::types::GLint,
+                count as gl::types::GLsizei,1,
+                0,
+                gl::RGBA,
+                gl::FLOAT,
+                rects.as_ptr() as *const gl::types::GLvoid,
+            );
+            gl::TexParameteri(gl::TEXTURE_2D,gl::TEXTURE_MIN_FILTER,gl::NEAREST as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D,gl::TEXTURE_MAG_FILTER,gl::NEAREST as gl::types::GLint);
+            gl::BindTexture(gl::TEXTURE_2D,self.layer_texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::R16UI as gl::types::GLint,
+                count as gl::types::GLsizei,1,
+                0,
+                gl::RED_INTEGER,
+                gl::UNSIGNED_SHORT,
+                layers.as_ptr() as *const gl::types::GLvoid,
+            );
+            gl::TexParameteri(gl::TEXTURE_2D,gl::TEXTURE_MIN_FILTER,gl::NEAREST as gl::types::GLint);
+            gl::TexParameteri(gl::TEXTURE_2D,gl::TEXTURE_MAG_FILTER,gl::NEAREST as gl::types::GLint);
+        }
+    }
+
+    /// Replace a single tile index at runtime.
+    /// # Arguments
+    /// * `x` - Tile column.
+    /// * `y` - Tile row.
+    /// * `id` - New atlas index for the tile.
+    pub fn set_tile(&self,x: usize,y: usize,id: u16) {
+        self.map_texture.set_index(x,y,id);
+    }
+
+    /// Set the tile size in framebuffer pixels, driving the map zoom.
+    pub fn set_tile_size(&self,size: Vec2<f32>) {
+        self.tile_size.set(size);
+    }
+
+    /// Set the absolute camera position, in tiles.
+    pub fn set_offset(&self,offset: Vec2<f32>) {
+        self.offset.set(offset);
+    }
+
+    /// Pan the camera by `delta` tiles.
+    pub fn scroll(&self,delta: Vec2<f32>) {
+        let offset = self.offset.get();
+        self.offset.set(vec2!(f32: offset.x() + delta.x(),offset.y() + delta.y()));
+    }
+}
+
+impl Layer for MapLayer {
+    fn framebuffer(&self) -> &gpu::Framebuffer {
+        &*self.framebuffer
+    }
+
+    fn render(&self) {
+        let fbsize = self.framebuffer.size;
+        let tile_size = self.tile_size.get();
+        self.engine.graphics.bind_target(&*self.framebuffer);
+        self.engine.graphics.clear(0x00000000);
+        self.engine.graphics.bind_texture(0,&self.map_texture);
+        self.atlas.borrow().bind(1);
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE2);
+            gl::BindTexture(gl::TEXTURE_2D,self.region_texture);
+            gl::ActiveTexture(gl::TEXTURE3);
+            gl::BindTexture(gl::TEXTURE_2D,self.layer_texture);
+        }
+        self.engine.graphics.bind_shader(&self.engine.map_shader);
+        self.engine.graphics.set_uniform_handle(self.u_map_texture,0);
+        self.engine.graphics.set_uniform_handle(self.u_atlas_texture,1);
+        self.engine.graphics.set_uniform_handle(self.u_region_texture,2);
+        self.engine.graphics.set_uniform_handle(self.u_layer_texture,3);
+        self.engine.graphics.set_uniform_handle(self.u_offset,self.offset.get());
+        self.engine.graphics.set_uniform_handle(self.u_pixels_per_layer,vec2!(f32: fbsize.x() as f32,fbsize.y() as f32));
+        self.engine.graphics.set_uniform_handle(self.u_tiles_per_pixel,vec2!(f32: 1.0 / tile_size.x(),1.0 / tile_size.y()));
+        self.engine.graphics.set_uniform_handle(self.u_maps_per_tile,vec2!(f32: 1.0 / (self.width as f32),1.0 / (self.height as f32)));
+        self.engine.graphics.bind_vertexbuffer(&self.engine.quad_vertexbuffer);
+        self.engine.graphics.draw_triangle_fan(4);
+    }
+}
+
+impl Drop for MapLayer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1,&self.region_texture);
+            gl::DeleteTextures(1,&self.layer_texture);
+        }
+    }
+}
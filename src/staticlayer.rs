@@ -17,7 +17,7 @@ impl StaticLayer {
         engine.graphics.clear(0xFFFFFF00);
         engine.graphics.bind_texture(0,&texture);
         engine.graphics.bind_shader(&engine.static_shader);
-        engine.graphics.set_uniform("u_texture",0);
+        engine.graphics.set_uniform_handle(engine.static_u_texture,0);
         engine.graphics.bind_vertexbuffer(&engine.quad_vertexbuffer);
         engine.graphics.draw_triangle_fan(4);
         Ok(StaticLayer {
@@ -0,0 +1,85 @@
+// G - stream buffer
+// Desmond Germans, 2020
+
+use crate::*;
+use std::rc::Rc;
+use std::marker::PhantomData;
+
+/// Number of rotating backing buffers in a `StreamBuffer`.
+pub const STREAM_FRAMES: usize = 3;
+
+/// Ring of per-frame vertex buffers for streaming dynamic uploads.
+///
+/// A single buffer that is re-filled every frame forces the driver to orphan
+/// and sync against in-flight frames. `StreamBuffer` keeps `STREAM_FRAMES`
+/// backing buffers and writes to the slot belonging to the current frame, which
+/// the GPU is not reading yet, so uploads never stall on previous frames.
+pub struct StreamBuffer<T> {
+    _graphics: Rc<gpu::Graphics>,
+    vbos: [gl::types::GLuint; STREAM_FRAMES],
+    capacity: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T> StreamBuffer<T> {
+    /// Create new stream buffer sized for `capacity` elements per frame.
+    /// # Arguments
+    /// * `graphics` - GPU Graphics context to create the buffer for.
+    /// * `capacity` - Maximum number of `T` written in a single frame.
+    pub fn new(graphics: &Rc<gpu::Graphics>,capacity: usize) -> Result<StreamBuffer<T>,EngineError> {
+        let mut vbos: [gl::types::GLuint; STREAM_FRAMES] = [0; STREAM_FRAMES];
+        unsafe {
+            gl::GenBuffers(STREAM_FRAMES as gl::types::GLsizei,vbos.as_mut_ptr());
+            for vbo in vbos.iter() {
+                gl::BindBuffer(gl::ARRAY_BUFFER,*vbo);
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    (capacity * std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                    std::ptr::null(),
+                    gl::STREAM_DRAW,
+                );
+            }
+        }
+        Ok(StreamBuffer {
+            _graphics: Rc::clone(graphics),
+            vbos: vbos,
+            capacity: capacity,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Write this frame's contents into the slot the GPU is not reading.
+    /// # Arguments
+    /// * `frame` - Current frame index (see `Engine::frame`).
+    /// * `data` - Contents to upload, at most `capacity` elements.
+    pub fn write(&self,frame: usize,data: &[T]) {
+        assert!(data.len() <= self.capacity,"StreamBuffer::write: {} elements exceed capacity {}",data.len(),self.capacity);
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER,self.vbos[frame % STREAM_FRAMES]);
+            gl::BufferSubData(
+                gl::ARRAY_BUFFER,
+                0,
+                (data.len() * std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+    }
+
+    /// Buffer object backing the given frame's slot.
+    pub fn slot(&self,frame: usize) -> gl::types::GLuint {
+        self.vbos[frame % STREAM_FRAMES]
+    }
+
+    /// Maximum number of elements per frame.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T> Drop for StreamBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(STREAM_FRAMES as gl::types::GLsizei,self.vbos.as_ptr());
+        }
+    }
+}